@@ -0,0 +1,63 @@
+use std::env;
+use std::process::Command;
+
+/// Container engine used to run image and container subcommands.
+///
+/// Docker and Podman are compatible enough for this tool's purposes (image
+/// labels, `inspect` JSON shape, build output) that the only real
+/// difference is which binary gets invoked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Engine {
+    Docker,
+    Podman,
+}
+
+impl Engine {
+    /// Autodetect the engine to use by probing `docker` then `podman` on
+    /// `PATH`, preferring Docker when both are present.
+    pub fn detect() -> Self {
+        if Self::Docker.is_on_path() {
+            Engine::Docker
+        } else if Self::Podman.is_on_path() {
+            Engine::Podman
+        } else {
+            Engine::Docker
+        }
+    }
+
+    /// Start building a [`Command`] invoking this engine's binary.
+    pub fn command(self) -> Command {
+        Command::new(self.binary())
+    }
+
+    fn binary(self) -> &'static str {
+        match self {
+            Engine::Docker => "docker",
+            Engine::Podman => "podman",
+        }
+    }
+
+    fn is_on_path(self) -> bool {
+        env::var_os("PATH")
+            .map(|paths| env::split_paths(&paths).any(|dir| dir.join(self.binary()).is_file()))
+            .unwrap_or(false)
+    }
+}
+
+impl std::str::FromStr for Engine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "docker" => Ok(Engine::Docker),
+            "podman" => Ok(Engine::Podman),
+            _ => Err(format!("Invalid engine '{}'", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Engine {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.binary())
+    }
+}
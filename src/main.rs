@@ -1,13 +1,19 @@
-use core::str;
 use std::{
-    fs,
-    io::{self, Read, Write},
+    env, fs, io,
     path::PathBuf,
-    process::{Command, Stdio},
+    process::{self, Stdio},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use argh::FromArgs;
 
+mod engine;
+mod manifest;
+mod remote;
+
+use engine::Engine;
+use manifest::ImageRecord;
+
 macro_rules! string_enum {
     (
         #[string_enum(name = $name_string:literal, doc = $doc:literal)]
@@ -16,6 +22,7 @@ macro_rules! string_enum {
         }
     ) => {
         #[doc = $doc]
+        #[derive(Clone, Copy)]
         enum $name {
             $($variant),*
         }
@@ -52,6 +59,16 @@ string_enum! {
     }
 }
 
+impl Architecture {
+    /// The `docker buildx build --platform` value for this architecture.
+    fn buildx_platform(&self) -> &'static str {
+        match self {
+            Architecture::X86_64 => "linux/amd64",
+            Architecture::Aarch64 => "linux/arm64",
+        }
+    }
+}
+
 string_enum! {
     #[string_enum(
         name = "Zig version",
@@ -66,9 +83,9 @@ string_enum! {
 #[derive(FromArgs)]
 #[argh(subcommand, name = "build")]
 struct BuildCommand {
-    /// target architecture
+    /// target architecture; repeat to build multiple platforms with buildx
     #[argh(option, short = 'a', long = "arch")]
-    architecture: Architecture,
+    architecture: Vec<Architecture>,
 
     /// version of zig to install
     #[argh(option, default = "ZigVersion::V0_13_0")]
@@ -86,18 +103,126 @@ struct BuildCommand {
 /// Prune built images.
 #[derive(FromArgs)]
 #[argh(subcommand, name = "clean")]
-struct CleanCommand {}
+struct CleanCommand {
+    /// only remove images built for this architecture
+    #[argh(option)]
+    arch: Option<Architecture>,
+
+    /// only remove images built from this Spade revision
+    #[argh(option)]
+    spade_rev: Option<String>,
+
+    /// only remove images built from this swim revision
+    #[argh(option)]
+    swim_rev: Option<String>,
+
+    /// only remove images older than this duration, e.g. "7d", "12h", "30m"
+    #[argh(option, from_str_fn(parse_duration))]
+    older_than: Option<Duration>,
+}
+
+/// Parse a duration written as an integer followed by a unit suffix: `s`,
+/// `m`, `h`, `d`, or `w`.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let unit = s
+        .chars()
+        .last()
+        .ok_or_else(|| format!("invalid duration '{}'", s))?;
+    let amount = &s[..s.len() - unit.len_utf8()];
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", s))?;
+    let seconds = match unit {
+        's' => amount,
+        'm' => amount * 60,
+        'h' => amount * 60 * 60,
+        'd' => amount * 60 * 60 * 24,
+        'w' => amount * 60 * 60 * 24 * 7,
+        _ => {
+            return Err(format!(
+                "invalid duration unit in '{}': expected one of s, m, h, d, w",
+                s
+            ))
+        }
+    };
+    Ok(Duration::from_secs(seconds))
+}
+
+/// List tracked images.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "list")]
+struct ListCommand {}
+
+/// Run `swim build` (or an arbitrary command) inside a built image.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "run")]
+struct RunCommand {
+    /// select an image by hash prefix; defaults to the most recently built image
+    #[argh(option)]
+    hash: Option<String>,
+
+    /// command to run inside the container in place of `swim build`
+    #[argh(positional)]
+    command: Vec<String>,
+}
+
+/// Manage the data volumes used to provision project directories for `run` on a remote engine.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "volume")]
+struct VolumeCommand {
+    #[argh(subcommand)]
+    action: VolumeAction,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum VolumeAction {
+    Create(VolumeCreateCommand),
+    Remove(VolumeRemoveCommand),
+}
+
+/// Create and populate the data volume for a build context.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "create")]
+struct VolumeCreateCommand {
+    /// path to the build context to provision; defaults to the current directory
+    #[argh(positional, default = "PathBuf::from(\".\")")]
+    context: PathBuf,
+}
+
+/// Remove the data volume for a build context.
+#[derive(FromArgs)]
+#[argh(subcommand, name = "remove")]
+struct VolumeRemoveCommand {
+    /// path to the build context whose volume should be removed; defaults to the current directory
+    #[argh(positional, default = "PathBuf::from(\".\")")]
+    context: PathBuf,
+}
 
 #[derive(FromArgs)]
 #[argh(subcommand)]
 enum Subcommand {
     Build(BuildCommand),
     Clean(CleanCommand),
+    Run(RunCommand),
+    List(ListCommand),
+    Volume(VolumeCommand),
 }
 
 /// Manage Spade docker images.
 #[derive(FromArgs)]
 struct CliArgs {
+    /// container engine to use (`docker` or `podman`); autodetected from
+    /// `PATH` by default
+    #[argh(option, default = "Engine::detect()")]
+    engine: Engine,
+
+    /// assume a remote daemon (also inferred from `DOCKER_HOST`) and
+    /// provision the `run` project directory through a data volume instead
+    /// of a bind mount
+    #[argh(switch)]
+    remote: bool,
+
     #[argh(subcommand)]
     subcommand: Subcommand,
 }
@@ -110,115 +235,320 @@ fn init_log_if_missing() -> io::Result<()> {
     fs::create_dir_all(data_dir())
 }
 
-fn log_image(hash: &str) -> io::Result<()> {
-    let mut logged_images = retrieve_logged_images()?;
-    if !logged_images.contains(&hash.to_string()) {
-        logged_images.push(hash.to_string());
+/// Whether a record matches all filters given on the `clean` subcommand.
+fn matches_clean_filter(record: &ImageRecord, clean_command: &CleanCommand, now: SystemTime) -> bool {
+    if let Some(arch) = &clean_command.arch {
+        if record.architecture != arch.to_string() {
+            return false;
+        }
+    }
+    if let Some(spade_rev) = &clean_command.spade_rev {
+        if &record.spade_rev != spade_rev {
+            return false;
+        }
     }
-    try_update_log(&logged_images)
+    if let Some(swim_rev) = &clean_command.swim_rev {
+        if &record.swim_rev != swim_rev {
+            return false;
+        }
+    }
+    if let Some(older_than) = clean_command.older_than {
+        let built_at = UNIX_EPOCH + Duration::from_secs(record.built_at);
+        let age = now.duration_since(built_at).unwrap_or_default();
+        if age < older_than {
+            return false;
+        }
+    }
+    true
 }
 
-fn retrieve_logged_images() -> io::Result<Vec<String>> {
-    let log_file = data_dir().join("hashes.txt");
-    if log_file.exists() {
-        let contents =
-            String::from_utf8(fs::read(log_file)?).expect("bug: non utf8 data written to log file");
-        Ok(contents.split("\n").map(str::to_string).collect())
-    } else {
-        Ok(vec![])
+/// The path built projects are mounted at inside the container.
+const PROJECT_WORKDIR: &str = "/workspace";
+
+/// Resolve a logged image from an optional hash prefix, defaulting to the
+/// most recently built image.
+fn select_image(logged_images: &[ImageRecord], hash_prefix: Option<&str>) -> String {
+    match hash_prefix {
+        Some(prefix) => logged_images
+            .iter()
+            .find(|record| record.hash.starts_with(prefix))
+            .unwrap_or_else(|| {
+                eprintln!("Error: no logged image matches hash prefix '{}'", prefix);
+                process::exit(1);
+            })
+            .hash
+            .clone(),
+        None => logged_images
+            .last()
+            .unwrap_or_else(|| {
+                eprintln!("Error: no images have been built yet");
+                process::exit(1);
+            })
+            .hash
+            .clone(),
     }
 }
 
-fn try_update_log(new_log: &[String]) -> io::Result<()> {
-    let temp_file = data_dir().join("hashes.temp.txt");
-    let log_file = data_dir().join("hashes.txt");
-    fs::write(&temp_file, new_log.join("\n"))?;
-    fs::rename(temp_file, log_file)
+/// Build a single-platform image, recovering its hash from `--iidfile`.
+///
+/// Unlike `run`'s bind mount, the `.` build context here is never routed
+/// through a remote data volume: `docker build`/`buildx build` already tar
+/// up and stream the context to a remote daemon over the same API
+/// connection used for everything else, bind mount or not, so there is
+/// nothing for `--remote` to fix on this path.
+fn build_single_platform(engine: Engine, build_command: BuildCommand) -> io::Result<()> {
+    let architecture = *build_command
+        .architecture
+        .first()
+        .expect("caller (main) validates at least one --arch is present");
+    let iidfile = env::temp_dir().join(format!("spade-docker-iid-{}", process::id()));
+
+    let status = engine
+        .command()
+        .arg("build")
+        .args([
+            "--build-arg",
+            &format!("TARGET_PLATFORM={}", architecture),
+        ])
+        .args([
+            "--build-arg",
+            &format!("ZIG_VERSION={}", build_command.zig_version),
+        ])
+        .args([
+            "--build-arg",
+            &format!("SPADE_REV={}", build_command.spade_rev),
+        ])
+        .args([
+            "--build-arg",
+            &format!("SWIM_REV={}", build_command.swim_rev),
+        ])
+        .arg(".")
+        .args(["--progress", "plain"])
+        .arg("--iidfile")
+        .arg(&iidfile)
+        .stderr(Stdio::inherit())
+        .spawn()?
+        .wait()?;
+
+    if !status.success() {
+        process::exit(status.code().unwrap_or(1));
+    }
+
+    let image_id = fs::read_to_string(&iidfile)?;
+    fs::remove_file(&iidfile).ok();
+    let hash = image_id
+        .trim()
+        .strip_prefix("sha256:")
+        .expect("`--iidfile` did not contain a sha256 image id");
+    manifest::log_image(
+        &data_dir(),
+        ImageRecord::now(
+            hash.to_string(),
+            architecture.to_string(),
+            build_command.zig_version.to_string(),
+            build_command.spade_rev,
+            build_command.swim_rev,
+        ),
+    )
+}
+
+/// Build a multi-platform image with `docker buildx build`, logging one
+/// manifest record per requested platform so the logical build is tracked
+/// as its constituent images.
+///
+/// A single `buildx build --platform a,b` invocation with neither `--push`
+/// nor `--load` has no output target: the result lives only in the build
+/// cache, isn't pullable, and isn't runnable locally, and every platform
+/// would share one manifest-list digest with no way to tell them apart.
+/// Instead, build and `--load` each platform one at a time, so each gets
+/// its own locally runnable image and its own digest in the manifest.
+///
+/// As with the single-platform path, the `.` context itself is not routed
+/// through a remote data volume — buildx streams it to the daemon itself.
+fn build_multi_platform(engine: Engine, build_command: BuildCommand) -> io::Result<()> {
+    let data_dir = data_dir();
+
+    for &architecture in &build_command.architecture {
+        let metadata_file = env::temp_dir().join(format!(
+            "spade-docker-metadata-{}-{}.json",
+            process::id(),
+            architecture
+        ));
+
+        let status = engine
+            .command()
+            .args(["buildx", "build"])
+            .args(["--platform", architecture.buildx_platform()])
+            .args([
+                "--build-arg",
+                &format!("TARGET_PLATFORM={}", architecture),
+            ])
+            .args([
+                "--build-arg",
+                &format!("ZIG_VERSION={}", build_command.zig_version),
+            ])
+            .args([
+                "--build-arg",
+                &format!("SPADE_REV={}", build_command.spade_rev),
+            ])
+            .args([
+                "--build-arg",
+                &format!("SWIM_REV={}", build_command.swim_rev),
+            ])
+            .arg(".")
+            .args(["--progress", "plain"])
+            .arg("--load")
+            .arg("--metadata-file")
+            .arg(&metadata_file)
+            .stderr(Stdio::inherit())
+            .spawn()?
+            .wait()?;
+
+        if !status.success() {
+            process::exit(status.code().unwrap_or(1));
+        }
+
+        let metadata_contents = fs::read_to_string(&metadata_file)?;
+        fs::remove_file(&metadata_file).ok();
+        let metadata: serde_json::Value = serde_json::from_str(&metadata_contents)?;
+        let digest = metadata["containerimage.digest"]
+            .as_str()
+            .expect("buildx metadata file did not contain `containerimage.digest`");
+
+        manifest::log_image(
+            &data_dir,
+            ImageRecord::now(
+                digest.to_string(),
+                architecture.to_string(),
+                build_command.zig_version.to_string(),
+                build_command.spade_rev.clone(),
+                build_command.swim_rev.clone(),
+            ),
+        )?;
+    }
+
+    Ok(())
 }
 
 fn main() -> io::Result<()> {
     init_log_if_missing()?;
 
-    match argh::from_env::<CliArgs>().subcommand {
+    let cli = argh::from_env::<CliArgs>();
+
+    match cli.subcommand {
         Subcommand::Build(build_command) => {
-            let mut stderr = Command::new("docker")
-                .arg("build")
-                .args([
-                    "--build-arg",
-                    &format!("TARGET_PLATFORM={}", build_command.architecture),
-                ])
-                .args([
-                    "--build-arg",
-                    &format!("ZIG_VERSION={}", build_command.zig_version),
-                ])
-                .args([
-                    "--build-arg",
-                    &format!("SPADE_REV={}", build_command.spade_rev),
-                ])
-                .args([
-                    "--build-arg",
-                    &format!("SWIM_REV={}", build_command.swim_rev),
-                ])
-                .arg(".")
-                .args(["--progress", "plain"])
-                .stderr(Stdio::piped())
-                .spawn()?
-                .stderr
-                .unwrap();
-
-            let mut stderr_captured = String::new();
-            let mut buffer = [0; 1024];
-            while let Ok(amount) = stderr.read(&mut buffer) {
-                if amount == 0 {
-                    break;
-                }
-                stderr_captured.push_str(
-                    str::from_utf8(&buffer[0..amount])
-                        .expect("`docker build` produced invalid utf8 output"),
-                );
-                io::stderr()
-                    .write_all(&buffer[0..amount])
-                    .expect("failed to write to stderr");
-                io::stderr().flush().expect("failed to flush stderr");
+            if build_command.architecture.is_empty() {
+                eprintln!("Error: at least one `--arch` is required");
+                process::exit(1);
             }
 
-            let last_line = stderr_captured
-                .lines()
-                .find(|line| line.contains("writing image sha256:"))
-                .expect("`docker build` did not write image");
-            let hash = last_line
-                .split(' ')
-                .map(str::trim)
-                .find_map(|segment| segment.strip_prefix("sha256:"))
-                .expect("no hash in `docker build` output");
-            log_image(hash)
+            if build_command.architecture.len() > 1 {
+                build_multi_platform(cli.engine, build_command)
+            } else {
+                build_single_platform(cli.engine, build_command)
+            }
         }
-        Subcommand::Clean(_clean_command) => {
-            let logged_images = retrieve_logged_images()?;
-            for (i, image_hash) in logged_images.iter().enumerate() {
-                let image_info_output = Command::new("docker")
+        Subcommand::Clean(clean_command) => {
+            let logged_images = manifest::load(&data_dir())?;
+            let now = SystemTime::now();
+            let mut surviving_images = Vec::new();
+
+            for record in logged_images {
+                if !matches_clean_filter(&record, &clean_command, now) {
+                    surviving_images.push(record);
+                    continue;
+                }
+
+                let image_info_output = cli
+                    .engine
+                    .command()
                     .arg("image")
                     .arg("inspect")
-                    .arg(image_hash)
+                    .arg(&record.hash)
                     .output()?;
                 let stdout = String::from_utf8(image_info_output.stdout)
                     .expect("`docker image inspect` output was unvalid utf8");
                 let image_info: serde_json::Value = serde_json::from_str(&stdout)?;
-                if image_info[0]["Config"]["Labels"]["tool"]
+                let is_ours = image_info[0]["Config"]["Labels"]["tool"]
                     .as_str()
                     .map(|value| value == "spade-docker")
-                    .unwrap_or_default()
-                {
-                    let remove_status = Command::new("docker")
-                        .args(["rmi", "-f", image_hash])
-                        .spawn()?
-                        .wait()?;
-                    if remove_status.success() {
-                        try_update_log(&logged_images[i + 1..])?;
-                    }
+                    .unwrap_or_default();
+
+                if !is_ours {
+                    surviving_images.push(record);
+                    continue;
+                }
+
+                let remove_status = cli
+                    .engine
+                    .command()
+                    .args(["rmi", "-f", &record.hash])
+                    .spawn()?
+                    .wait()?;
+                if !remove_status.success() {
+                    surviving_images.push(record);
                 }
             }
+
+            manifest::save(&data_dir(), &surviving_images)
+        }
+        Subcommand::Run(run_command) => {
+            let logged_images = manifest::load(&data_dir())?;
+            let hash = select_image(&logged_images, run_command.hash.as_deref());
+
+            let project_dir = env::current_dir()?;
+
+            let mount = if remote::is_remote(cli.remote) {
+                let volume = remote::create_volume(cli.engine, &project_dir)?;
+                format!("{}:{}", volume, PROJECT_WORKDIR)
+            } else {
+                format!("{}:{}", project_dir.display(), PROJECT_WORKDIR)
+            };
+
+            let mut command = cli.engine.command();
+            command
+                .arg("run")
+                .arg("--rm")
+                .args(["-v", &mount])
+                .args(["--workdir", PROJECT_WORKDIR])
+                .arg(&hash);
+
+            if run_command.command.is_empty() {
+                command.args(["swim", "build"]);
+            } else {
+                command.args(&run_command.command);
+            }
+
+            let status = command.spawn()?.wait()?;
+            process::exit(status.code().unwrap_or(1));
+        }
+        Subcommand::List(_list_command) => {
+            let logged_images = manifest::load(&data_dir())?;
+            println!(
+                "{:<14}{:<10}{:<10}{:<14}{:<14}{:<12}",
+                "HASH", "ARCH", "ZIG", "SPADE_REV", "SWIM_REV", "BUILT_AT"
+            );
+            for record in &logged_images {
+                println!(
+                    "{:<14}{:<10}{:<10}{:<14}{:<14}{:<12}",
+                    &record.hash[..record.hash.len().min(12)],
+                    record.architecture,
+                    record.zig_version,
+                    record.spade_rev,
+                    record.swim_rev,
+                    record.built_at,
+                );
+            }
             Ok(())
         }
+        Subcommand::Volume(volume_command) => match volume_command.action {
+            VolumeAction::Create(create_command) => {
+                remote::create_volume(cli.engine, &create_command.context)?;
+                Ok(())
+            }
+            VolumeAction::Remove(remove_command) => {
+                remote::remove_volume(cli.engine, &remove_command.context)
+            }
+        },
     }
 }
@@ -0,0 +1,93 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// Metadata about one built image, tracked on disk alongside the images
+/// themselves.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ImageRecord {
+    pub hash: String,
+    pub architecture: String,
+    pub zig_version: String,
+    pub spade_rev: String,
+    pub swim_rev: String,
+    /// Unix timestamp, in seconds, of when the image finished building.
+    pub built_at: u64,
+}
+
+impl ImageRecord {
+    pub fn now(hash: String, architecture: String, zig_version: String, spade_rev: String, swim_rev: String) -> Self {
+        let built_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Self {
+            hash,
+            architecture,
+            zig_version,
+            spade_rev,
+            swim_rev,
+            built_at,
+        }
+    }
+}
+
+fn manifest_path(data_dir: &Path) -> std::path::PathBuf {
+    data_dir.join("manifest.json")
+}
+
+fn legacy_log_path(data_dir: &Path) -> std::path::PathBuf {
+    data_dir.join("hashes.txt")
+}
+
+/// Load the manifest, migrating a legacy newline-separated `hashes.txt` log
+/// to the structured format on first run.
+pub fn load(data_dir: &Path) -> io::Result<Vec<ImageRecord>> {
+    let manifest_file = manifest_path(data_dir);
+    if manifest_file.exists() {
+        let contents = fs::read_to_string(manifest_file)?;
+        return Ok(serde_json::from_str(&contents)?);
+    }
+
+    let legacy_file = legacy_log_path(data_dir);
+    if legacy_file.exists() {
+        let contents = fs::read_to_string(&legacy_file)?;
+        let migrated: Vec<ImageRecord> = contents
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|hash| ImageRecord {
+                hash: hash.to_string(),
+                architecture: "unknown".to_string(),
+                zig_version: "unknown".to_string(),
+                spade_rev: "unknown".to_string(),
+                swim_rev: "unknown".to_string(),
+                built_at: 0,
+            })
+            .collect();
+        save(data_dir, &migrated)?;
+        fs::remove_file(legacy_file)?;
+        return Ok(migrated);
+    }
+
+    Ok(vec![])
+}
+
+/// Atomically persist the manifest via a temp-file-then-rename.
+pub fn save(data_dir: &Path, records: &[ImageRecord]) -> io::Result<()> {
+    let temp_file = data_dir.join("manifest.temp.json");
+    let manifest_file = manifest_path(data_dir);
+    fs::write(&temp_file, serde_json::to_string_pretty(records)?)?;
+    fs::rename(temp_file, manifest_file)
+}
+
+/// Record a newly built image, replacing any existing record with the same
+/// hash.
+pub fn log_image(data_dir: &Path, record: ImageRecord) -> io::Result<()> {
+    let mut records = load(data_dir)?;
+    records.retain(|existing| existing.hash != record.hash);
+    records.push(record);
+    save(data_dir, &records)
+}
@@ -0,0 +1,115 @@
+use std::env;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::engine::Engine;
+
+/// Whether commands should assume a remote daemon is in play, either because
+/// the user passed `--remote` or `DOCKER_HOST` points somewhere.
+pub fn is_remote(remote_flag: bool) -> bool {
+    remote_flag || env::var_os("DOCKER_HOST").is_some()
+}
+
+/// FNV-1a, 64-bit. Unlike `std::collections::hash_map::DefaultHasher`, whose
+/// docs explicitly disclaim stability across Rust versions, this algorithm's
+/// output never changes, so a toolchain upgrade can't silently rename (and
+/// orphan) an already-populated volume.
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    let mut hash = FNV_OFFSET_BASIS;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+/// Deterministic data-volume name for a build context, so repeated runs
+/// against the same project reuse the same volume.
+fn volume_name(context: &Path) -> String {
+    let absolute = context.canonicalize().unwrap_or_else(|_| context.to_path_buf());
+    let hash = fnv1a_hash(absolute.to_string_lossy().as_bytes());
+    format!("spade-docker-{:016x}", hash)
+}
+
+/// Create (if missing) and populate the data volume mirroring `context`,
+/// returning its name. Mirrors cross's remote-engine model: since a bind
+/// mount can't reach across the daemon connection, the context is tarred up
+/// client-side and streamed over a pipe into a throwaway container that
+/// unpacks it into the volume, so no shared filesystem with the daemon host
+/// is assumed. The volume is cleared first: `docker volume create` is a
+/// no-op on an existing volume and extracting a tar only adds/overwrites
+/// entries, so without this a file removed locally would linger in the
+/// volume across every later `--remote` run.
+pub fn create_volume(engine: Engine, context: &Path) -> io::Result<String> {
+    let name = volume_name(context);
+    let absolute = context.canonicalize()?;
+
+    let status = engine
+        .command()
+        .args(["volume", "create", &name])
+        .spawn()?
+        .wait()?;
+    if !status.success() {
+        return Err(io::Error::other("failed to create data volume"));
+    }
+
+    let status = engine
+        .command()
+        .arg("run")
+        .arg("--rm")
+        .args(["-v", &format!("{}:/data", name)])
+        .arg("busybox")
+        .args(["sh", "-c", "find /data -mindepth 1 -delete"])
+        .spawn()?
+        .wait()?;
+    if !status.success() {
+        return Err(io::Error::other("failed to clear data volume"));
+    }
+
+    let mut tar = Command::new("tar")
+        .args(["-cf", "-", "-C"])
+        .arg(&absolute)
+        .arg(".")
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let tar_stdout = tar.stdout.take().expect("tar stdout was piped");
+
+    let status = engine
+        .command()
+        .arg("run")
+        .arg("--rm")
+        .arg("-i")
+        .args(["-v", &format!("{}:/data", name)])
+        .arg("busybox")
+        .args(["sh", "-c", "tar -xf - -C /data"])
+        .stdin(Stdio::from(tar_stdout))
+        .spawn()?
+        .wait()?;
+
+    if !tar.wait()?.success() {
+        return Err(io::Error::other("failed to tar build context"));
+    }
+    if !status.success() {
+        return Err(io::Error::other("failed to populate data volume"));
+    }
+
+    Ok(name)
+}
+
+/// Tear down the data volume for `context`.
+pub fn remove_volume(engine: Engine, context: &Path) -> io::Result<()> {
+    let name = volume_name(context);
+    let status = engine
+        .command()
+        .args(["volume", "rm", "-f", &name])
+        .spawn()?
+        .wait()?;
+    if !status.success() {
+        return Err(io::Error::other("failed to remove data volume"));
+    }
+    Ok(())
+}